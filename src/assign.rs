@@ -0,0 +1,200 @@
+use crate::condition;
+use crate::saver::Saver;
+use crate::{NUM_FRETS, NUM_STRINGS};
+use csv::StringRecord;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::{stdin, stdout, Write};
+use std::path::Path;
+
+// notes that were played without being mapped to a key, counted per
+// channel and note so that `kitara assign` can offer to map them later
+pub struct Pending {
+    saver: Saver,
+    counts: BTreeMap<(u8, i32), Entry>,
+}
+
+struct Entry {
+    // the string and fret the note was played on, worked out with the
+    // tuning kitara ran with; unknown when the channel is not in the config
+    position: Option<(usize, i32)>,
+    count: u32,
+}
+
+impl Pending {
+    // loads a csv file with rows of <channel>,<note>,<string>,<fret>,<count>
+    // where string and fret are empty when the channel is not in the config
+    pub fn load(path: &Path) -> Pending {
+        let mut counts = BTreeMap::new();
+        if let Ok(contents) = fs::read_to_string(path) {
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .from_reader(contents.as_bytes());
+            for record in reader.records().flatten() {
+                let field = |i: usize| record.get(i).unwrap_or_default().trim();
+                let position = match (field(2), field(3)) {
+                    ("", "") => Ok(None),
+                    (gtr_string, gtr_fret) => gtr_string.parse()
+                        .and_then(|s| gtr_fret.parse().map(|f| Some((s, f)))),
+                };
+                match (field(0).parse(), field(1).parse(), position, field(4).parse()) {
+                    (Ok(channel), Ok(note), Ok(position), Ok(count)) => {
+                        counts.insert((channel, note), Entry { position, count });
+                    }
+                    _ => warning!("Ignoring invalid pending note {:?}", record),
+                }
+            }
+        }
+        Pending { saver: Saver::new(path, "pending notes"), counts }
+    }
+
+    pub fn save_in_background(&mut self) {
+        self.saver.save_in_background();
+    }
+
+    pub fn finish(&mut self) {
+        self.saver.finish();
+    }
+
+    pub fn record(&mut self, channel: u8, note: i32, position: Option<(usize, i32)>) {
+        let entry = self.counts.entry((channel, note)).or_insert(Entry { position, count: 0 });
+        entry.position = position;
+        entry.count += 1;
+        self.save();
+    }
+
+    fn save(&self) {
+        self.saver.save(self.to_csv());
+    }
+
+    fn to_csv(&self) -> Result<Vec<u8>, String> {
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(vec![]);
+        for ((channel, note), entry) in &self.counts {
+            let (gtr_string, gtr_fret) = match entry.position {
+                Some((s, f)) => (s.to_string(), f.to_string()),
+                None => (String::new(), String::new()),
+            };
+            writer
+                .write_record([channel.to_string(), note.to_string(), gtr_string, gtr_fret, entry.count.to_string()])
+                .map_err(|e| e.to_string())?;
+        }
+        writer.into_inner().map_err(|e| e.to_string())
+    }
+}
+
+// walks the pending notes, most played first, asking which key each of them
+// should type; the answers are written into the base layer of the config
+pub fn run(csv_config_filepath: &str, pending_filepath: &Path) -> Result<(), Box<dyn Error>> {
+    let contents = fs::read_to_string(csv_config_filepath)?;
+    let mut reader = csv::Reader::from_reader(contents.as_bytes());
+    let headers = reader.headers()?.clone();
+    let mut records = reader.records().collect::<Result<Vec<StringRecord>, _>>()?;
+    if records.len() < NUM_STRINGS {
+        return Err(format!("The config has {} strings instead of {}", records.len(), NUM_STRINGS).into());
+    }
+    // the base layer is made of the first rows of the config
+    let midi_channels = records[..NUM_STRINGS]
+        .iter()
+        .map(|row| row[0].parse::<u8>())
+        .collect::<Result<Vec<u8>, _>>()?;
+
+    let mut pending = Pending::load(pending_filepath);
+    if pending.counts.is_empty() {
+        println!("No unmapped notes were played");
+        return Ok(());
+    }
+    let mut notes: Vec<_> = pending.counts
+        .iter()
+        .map(|(n, e)| (*n, e.position, e.count))
+        .collect();
+    notes.sort_by_key(|&(_, _, count)| std::cmp::Reverse(count));
+
+    let mut assigned = 0;
+    for ((channel, note), position, count) in notes {
+        let played = format!("note {} on channel {} was played {} {}",
+                             note, channel, count, if count == 1 { "time" } else { "times" });
+        // the position was recorded with the tuning kitara ran with, but the
+        // channels of the config may have been edited since
+        let (gtr_string, gtr_fret) = match position {
+            Some((s, _)) if midi_channels.get(s) != Some(&channel) => {
+                println!("{}, but the config does not list channel {} for string {} anymore - skipped",
+                         played, channel, s);
+                pending.counts.remove(&(channel, note));
+                continue;
+            }
+            Some((s, f)) if f >= 0 && (f as usize) < NUM_FRETS => (s, f),
+            Some(_) => {
+                println!("{}, but it is outside of the fretboard - skipped", played);
+                pending.counts.remove(&(channel, note));
+                continue;
+            }
+            None => {
+                println!("{}, but the config has no string on channel {} - skipped", played, channel);
+                pending.counts.remove(&(channel, note));
+                continue;
+            }
+        };
+
+        let column = gtr_fret as usize + 1;
+        let current = records[gtr_string].get(column).unwrap_or_default().to_string();
+        if !current.is_empty() {
+            println!("{}, but it is mapped to {} by now", played, current);
+            pending.counts.remove(&(channel, note));
+            continue;
+        }
+
+        let answer = match ask(&played, gtr_string, gtr_fret)? {
+            Some(a) => a,
+            None => break,
+        };
+        if answer.is_empty() {
+            continue;
+        }
+
+        let mut fields: Vec<String> = records[gtr_string].iter().map(String::from).collect();
+        fields[column] = answer;
+        records[gtr_string] = StringRecord::from(fields);
+        pending.counts.remove(&(channel, note));
+        assigned += 1;
+    }
+
+    if assigned > 0 {
+        // keep the line endings the config was written with
+        let terminator = if contents.contains("\r\n") {
+            csv::Terminator::CRLF
+        } else {
+            csv::Terminator::Any(b'\n')
+        };
+        let mut writer = csv::WriterBuilder::new()
+            .terminator(terminator)
+            .from_path(csv_config_filepath)?;
+        writer.write_record(&headers)?;
+        for record in &records {
+            writer.write_record(record)?;
+        }
+        writer.flush()?;
+        println!("Assigned {} notes in {}", assigned, csv_config_filepath);
+    }
+    pending.save();
+    Ok(())
+}
+
+// asks until the answer is a cell kitara can load, None once stdin ends
+fn ask(played: &str, gtr_string: usize, gtr_fret: i32) -> Result<Option<String>, Box<dyn Error>> {
+    loop {
+        print!("{} (string={}, fret={}) - map it to? (enter skips) ", played, gtr_string, gtr_fret);
+        stdout().flush()?;
+        let mut answer = String::new();
+        if stdin().read_line(&mut answer)? == 0 {
+            return Ok(None);
+        }
+        let answer = answer.trim();
+        match condition::split(answer).map(|(c, _)| condition::validate(c)) {
+            Some(Err(e)) => println!("{}", e),
+            _ => return Ok(Some(answer.to_string())),
+        }
+    }
+}
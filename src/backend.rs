@@ -0,0 +1,133 @@
+use enigo::{Enigo, Key, KeyboardControllable};
+
+// the backends that can turn notes into keystrokes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    // types into the focused application (x11 on linux)
+    Enigo,
+    // only logs the keystrokes that would have been typed
+    DryRun,
+}
+
+impl Backend {
+    pub fn parse(name: &str) -> Option<Backend> {
+        match name {
+            "enigo" => Some(Backend::Enigo),
+            "dry-run" => Some(Backend::DryRun),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Enigo => "enigo",
+            Backend::DryRun => "dry-run",
+        }
+    }
+
+    // an enigo instance is created every time because
+    // it cannot be moved into the midi input thread
+    pub fn key_down(self, key: Key) {
+        match self {
+            Backend::Enigo => Enigo::new().key_down(key),
+            Backend::DryRun => info!("dry-run: key down {:?}", key),
+        }
+    }
+
+    pub fn key_up(self, key: Key) {
+        match self {
+            Backend::Enigo => Enigo::new().key_up(key),
+            Backend::DryRun => info!("dry-run: key up {:?}", key),
+        }
+    }
+
+    pub fn key_click(self, key: Key) {
+        match self {
+            Backend::Enigo => Enigo::new().key_click(key),
+            Backend::DryRun => info!("dry-run: click {:?}", key),
+        }
+    }
+
+    pub fn key_sequence(self, text: &str) {
+        match self {
+            Backend::Enigo => Enigo::new().key_sequence(text),
+            Backend::DryRun => info!("dry-run: type {:?}", text),
+        }
+    }
+}
+
+// picks the backend to type with: the requested one if it can work in this
+// session or, when none was requested, enigo with a fallback to dry-run
+pub fn select(requested: Option<Backend>) -> Result<Backend, String> {
+    let missing = missing_display();
+    match (requested, missing) {
+        (Some(Backend::Enigo), Some(reason)) => Err(format!(
+            "Cannot use the enigo backend - {}\n{}",
+            reason,
+            HEADLESS_HELP
+        )),
+        (Some(backend), _) => Ok(backend),
+        (None, Some(reason)) => {
+            warning!("No display available - {}\n{}", reason, HEADLESS_HELP);
+            warning!("Falling back to the dry-run backend: notes are logged but no keys are typed.");
+            Ok(Backend::DryRun)
+        }
+        (None, None) => Ok(Backend::Enigo),
+    }
+}
+
+const HEADLESS_HELP: &str = "\
+kitara types by sending keystrokes to the graphical session, which is not reachable
+from this environment (e.g. over ssh or inside a container). Either run kitara from
+the desktop session, export DISPLAY (e.g. DISPLAY=:0) if a display server is running
+on this machine, or pass --backend dry-run to only log the keys that would be typed.
+The uinput and remote injection backends are not available in this build.";
+
+// macos and windows always have a window server session reachable by the
+// user, but on other unix systems enigo needs an x11 display (xdo)
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn missing_display() -> Option<String> {
+    let display = std::env::var("DISPLAY").ok().filter(|d| !d.is_empty());
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some_and(|d| !d.is_empty());
+    match (display, wayland) {
+        (Some(display), _) => probe_display(&display)
+            .err()
+            .map(|e| format!("DISPLAY is set to {} but the display cannot be reached ({})", display, e)),
+        (None, true) => Some("WAYLAND_DISPLAY is set but DISPLAY is not, enigo needs X11 (XWayland)".into()),
+        (None, false) => Some("neither DISPLAY nor WAYLAND_DISPLAY is set".into()),
+    }
+}
+
+// xdo cannot report a display that is gone (e.g. a leftover ssh -X session or
+// a container without the x11 socket) and crashes on the first key instead, so
+// the display is connected to once; DISPLAY is [host]:number[.screen]
+#[cfg(all(unix, not(target_os = "macos")))]
+fn probe_display(display: &str) -> std::io::Result<()> {
+    use std::io::{Error, ErrorKind};
+    use std::net::{TcpStream, ToSocketAddrs};
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    let invalid = || Error::new(ErrorKind::InvalidInput, "not a valid display name");
+    let colon = display.rfind(':').ok_or_else(invalid)?;
+    let host = &display[..colon];
+    let number: u16 = display[colon + 1..]
+        .split('.')
+        .next()
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(invalid)?;
+
+    if host.is_empty() || host == "unix" {
+        UnixStream::connect(format!("/tmp/.X11-unix/X{}", number)).map(drop)
+    } else {
+        // e.g. localhost:10.0 as forwarded by ssh -X
+        let port = 6000u16.checked_add(number).ok_or_else(invalid)?;
+        let address = (host, port).to_socket_addrs()?.next().ok_or_else(invalid)?;
+        TcpStream::connect_timeout(&address, Duration::from_secs(1)).map(drop)
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub fn missing_display() -> Option<String> {
+    None
+}
@@ -0,0 +1,172 @@
+use std::collections::HashSet;
+
+// a cell is only active while its condition holds, e.g. {{when:cc64>63}}SH
+// types shift only while the sustain pedal is down
+const PREFIX: &str = "{{when:";
+const SUFFIX: &str = "}}";
+
+// the live state of the midi controllers and notes, as far as kitara has seen it
+pub struct MidiState {
+    controllers: [u8; 128],
+    notes: HashSet<(u8, u8)>,
+}
+
+impl MidiState {
+    pub fn new() -> MidiState {
+        MidiState { controllers: [0; 128], notes: HashSet::new() }
+    }
+
+    pub fn control_change(&mut self, controller: u8, value: u8) {
+        self.controllers[usize::from(controller & 0x7F)] = value;
+    }
+
+    pub fn note_on(&mut self, channel: u8, note: u8) {
+        self.notes.insert((channel, note));
+    }
+
+    pub fn note_off(&mut self, channel: u8, note: u8) {
+        self.notes.remove(&(channel, note));
+    }
+
+    fn note_held(&self, note: u8) -> bool {
+        self.notes.iter().any(|&(_, n)| n == note)
+    }
+}
+
+// conditions are made of terms that must all hold, joined by &:
+// ccN>V, ccN>=V, ccN<V, ccN<=V, ccN=V (controller N compared to value V)
+// and noteN (note N is held on any channel), each negated by a leading !
+enum Term {
+    Controller(u8, Comparison, u8),
+    Note(u8),
+}
+
+enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+}
+
+// splits a cell into its condition and the key it is bound to
+pub fn split(cell: &str) -> Option<(&str, &str)> {
+    if !cell.starts_with(PREFIX) {
+        return None;
+    }
+    let rest = &cell[PREFIX.len()..];
+    let end = rest.find(SUFFIX)?;
+    Some((&rest[..end], &rest[end + SUFFIX.len()..]))
+}
+
+pub fn validate(condition: &str) -> Result<(), String> {
+    parse(condition).map(|_| ())
+}
+
+// invalid conditions never hold, but they are rejected when the config is loaded
+pub fn evaluate(condition: &str, midi: &MidiState) -> bool {
+    match parse(condition) {
+        Ok(terms) => terms.iter().all(|(negated, term)| negated ^ holds(term, midi)),
+        Err(_) => false,
+    }
+}
+
+fn holds(term: &Term, midi: &MidiState) -> bool {
+    match term {
+        Term::Note(note) => midi.note_held(*note),
+        Term::Controller(controller, comparison, value) => {
+            let current = midi.controllers[usize::from(*controller)];
+            match comparison {
+                Comparison::Greater => current > *value,
+                Comparison::GreaterOrEqual => current >= *value,
+                Comparison::Less => current < *value,
+                Comparison::LessOrEqual => current <= *value,
+                Comparison::Equal => current == *value,
+            }
+        }
+    }
+}
+
+fn parse(condition: &str) -> Result<Vec<(bool, Term)>, String> {
+    condition
+        .split('&')
+        .map(|term| {
+            let term = term.trim();
+            match term.strip_prefix('!') {
+                Some(t) => parse_term(t.trim()).map(|t| (true, t)),
+                None => parse_term(term).map(|t| (false, t)),
+            }
+        })
+        .collect()
+}
+
+fn parse_term(term: &str) -> Result<Term, String> {
+    let invalid = || format!("Invalid condition {}, expected e.g. cc64>63 or note40", term);
+    if let Some(note) = term.strip_prefix("note") {
+        return parse_midi_value(note).map(Term::Note).ok_or_else(invalid);
+    }
+    let controller = term.strip_prefix("cc").ok_or_else(invalid)?;
+    // two character operators are looked for first, so that >= is not read as >
+    let operators = [
+        (">=", Comparison::GreaterOrEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">", Comparison::Greater),
+        ("<", Comparison::Less),
+        ("=", Comparison::Equal),
+    ];
+    for (operator, comparison) in operators {
+        if let Some(i) = controller.find(operator) {
+            let number = parse_midi_value(&controller[..i]).ok_or_else(invalid)?;
+            let value = parse_midi_value(&controller[i + operator.len()..]).ok_or_else(invalid)?;
+            return Ok(Term::Controller(number, comparison, value));
+        }
+    }
+    Err(invalid())
+}
+
+// midi data bytes range from 0 to 127
+fn parse_midi_value(text: &str) -> Option<u8> {
+    text.trim().parse::<u8>().ok().filter(|v| *v < 128)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_the_condition_from_the_key() {
+        assert_eq!(split("{{when:cc64>63}}SH"), Some(("cc64>63", "SH")));
+        assert_eq!(split("SH"), None);
+        assert_eq!(split("{{when:cc64>63"), None);
+    }
+
+    #[test]
+    fn compares_controllers() {
+        let mut midi = MidiState::new();
+        assert!(!evaluate("cc64>=64", &midi));
+        midi.control_change(64, 64);
+        assert!(evaluate("cc64>=64", &midi));
+        assert!(!evaluate("cc64>64", &midi));
+        assert!(evaluate("cc64=64 & cc1<1", &midi));
+    }
+
+    #[test]
+    fn checks_held_notes() {
+        let mut midi = MidiState::new();
+        assert!(evaluate("!note40", &midi));
+        midi.note_on(1, 40);
+        assert!(!evaluate("!note40", &midi));
+        assert!(evaluate("note40", &midi));
+        midi.note_off(1, 40);
+        assert!(!evaluate("note40", &midi));
+    }
+
+    #[test]
+    fn rejects_invalid_conditions() {
+        assert!(validate("cc128>1").is_err());
+        assert!(validate("cc64>128").is_err());
+        assert!(validate("x").is_err());
+        assert!(validate("cc64>63&").is_err());
+        assert!(validate("cc64>63 & !note40").is_ok());
+    }
+}
@@ -0,0 +1,46 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// set once at startup by --quiet
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+// prints informational output (the mapping chart, connection
+// chatter and per-note logs), all of which --quiet suppresses
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !$crate::console::is_quiet() {
+            println!($($arg)*);
+        }
+    }
+}
+
+// operational problems go to stderr with a prefix naming their level, so that
+// e.g. `kitara ... 2>errors.log` keeps them apart from the per-note output
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        eprintln!("warning: {}", format_args!($($arg)*))
+    }
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        eprintln!("error: {}", format_args!($($arg)*))
+    }
+}
+
+// prints the single machine readable line that wrapper scripts can rely on,
+// made of key=value pairs; it is printed even when --quiet is given
+pub fn status(fields: &[(&str, String)]) {
+    let line: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    println!("kitara {}", line.join(" "));
+}
@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+// a dictionary of typos and their corrections, matched ignoring case
+#[derive(Default)]
+pub struct Corrections {
+    fixes: HashMap<String, String>,
+}
+
+impl Corrections {
+    // loads a csv file with a header and rows of <typo>,<fix>
+    pub fn load(csv: String) -> Result<Corrections, Box<dyn Error>> {
+        let mut fixes = HashMap::new();
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        for record in reader.records() {
+            let record = record?;
+            match (record.get(0), record.get(1)) {
+                (Some(typo), Some(fix)) if !typo.trim().is_empty() => {
+                    fixes.insert(typo.trim().to_lowercase(), fix.trim().to_string());
+                }
+                _ => return Err(format!("Invalid correction {:?}, expected <typo>,<fix>", record).into()),
+            }
+        }
+        Ok(Corrections { fixes })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fixes.is_empty()
+    }
+
+    // returns the correction for a typo, capitalized like the typo was
+    pub fn fix(&self, word: &str) -> Option<String> {
+        let fix = self.fixes.get(&word.to_lowercase())?;
+        if word.chars().next().is_some_and(char::is_uppercase) {
+            let mut chars = fix.chars();
+            Some(chars.next().map_or(String::new(), |first| first.to_uppercase().chain(chars).collect()))
+        } else {
+            Some(fix.clone())
+        }
+    }
+}
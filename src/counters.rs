@@ -0,0 +1,77 @@
+use crate::saver::Saver;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// named counters used by {{counter:...}} placeholders;
+// values are persisted next to the config file so that
+// numbering carries on after kitara is restarted
+pub struct Counters {
+    saver: Saver,
+    values: BTreeMap<String, i64>,
+}
+
+impl Counters {
+    // loads counters from a csv file with rows of <name>,<value>
+    // a missing file simply means that no counter has been used yet
+    pub fn load(path: &Path) -> Counters {
+        let mut values = BTreeMap::new();
+        if let Ok(contents) = fs::read_to_string(path) {
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .from_reader(contents.as_bytes());
+            for record in reader.records().flatten() {
+                if let (Some(name), Some(value)) = (record.get(0), record.get(1)) {
+                    match value.parse::<i64>() {
+                        Ok(v) => {
+                            values.insert(name.to_string(), v);
+                        }
+                        Err(_) => warning!("Ignoring invalid value for counter {}: {}", name, value),
+                    }
+                }
+            }
+        }
+        Counters { saver: Saver::new(path, "counters"), values }
+    }
+
+    pub fn save_in_background(&mut self) {
+        self.saver.save_in_background();
+    }
+
+    pub fn finish(&mut self) {
+        self.saver.finish();
+    }
+
+    // increments the counter and returns its new value (a new counter starts at 1)
+    pub fn take(&mut self, name: &str) -> i64 {
+        let value = self.values.entry(name.to_string()).or_insert(0);
+        *value += 1;
+        let taken = *value;
+        self.save();
+        taken
+    }
+
+    // returns the current value without changing it
+    pub fn peek(&self, name: &str) -> i64 {
+        self.values.get(name).copied().unwrap_or(0)
+    }
+
+    pub fn reset(&mut self, name: &str) {
+        self.values.insert(name.to_string(), 0);
+        self.save();
+    }
+
+    fn save(&self) {
+        self.saver.save(self.to_csv());
+    }
+
+    fn to_csv(&self) -> Result<Vec<u8>, String> {
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(vec![]);
+        for (name, value) in &self.values {
+            writer.write_record([name, &value.to_string()]).map_err(|e| e.to_string())?;
+        }
+        writer.into_inner().map_err(|e| e.to_string())
+    }
+}
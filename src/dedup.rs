@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// how long after a message its copy may arrive from another source
+pub const DEFAULT_WINDOW_MS: u64 = 30;
+
+struct Event {
+    at: Instant,
+    // the sources that delivered this message so far
+    sources: Vec<usize>,
+    key: Vec<u8>,
+}
+
+// drops messages that reach kitara from more than one source, e.g. from
+// several ports of a device or from its usb and bluetooth connections
+pub struct Dedup {
+    window: Duration,
+    recent: VecDeque<Event>,
+}
+
+impl Dedup {
+    pub fn new(window: Duration) -> Dedup {
+        Dedup { window, recent: VecDeque::new() }
+    }
+
+    // true when the same message already arrived from a different source within
+    // the window; repeats from the same source are always genuine new events
+    pub fn is_duplicate(&mut self, source: usize, message: &[u8]) -> bool {
+        if message.len() < 2 || self.window == Duration::from_millis(0) {
+            return false;
+        }
+        self.is_duplicate_at(Instant::now(), source, message)
+    }
+
+    fn is_duplicate_at(&mut self, now: Instant, source: usize, message: &[u8]) -> bool {
+        let key = key(message);
+        while self.recent.front().is_some_and(|e| now.duration_since(e.at) > self.window) {
+            self.recent.pop_front();
+        }
+
+        // the oldest event this source has not delivered yet is the one it
+        // copies; the event stays, as further sources may deliver it too
+        match self.recent.iter_mut().find(|e| e.key == key && !e.sources.contains(&source)) {
+            Some(event) => {
+                event.sources.push(source);
+                true
+            }
+            None => {
+                self.recent.push_back(Event { at: now, sources: vec![source], key });
+                false
+            }
+        }
+    }
+}
+
+// note velocities are not part of the key as they may differ from one transport
+// to another, and a note on with velocity 0 is the same release as a note off;
+// any other message, e.g. a control change, is only a copy when all of its bytes match
+fn key(message: &[u8]) -> Vec<u8> {
+    let channel = message[0] & 0x0F;
+    match message[0] >> 4 {
+        0x8 => vec![0x80 | channel, message[1]],
+        0x9 if message.get(2) == Some(&0) => vec![0x80 | channel, message[1]],
+        0x9 => vec![message[0], message[1]],
+        _ => message.to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRESS: [u8; 3] = [0x90, 40, 100];
+
+    fn dedup() -> Dedup {
+        Dedup::new(Duration::from_millis(DEFAULT_WINDOW_MS))
+    }
+
+    #[test]
+    fn drops_copies_from_other_sources() {
+        let mut dedup = dedup();
+        let now = Instant::now();
+        assert!(!dedup.is_duplicate_at(now, 0, &PRESS));
+        assert!(dedup.is_duplicate_at(now, 1, &[0x90, 40, 90]));
+        assert!(dedup.is_duplicate_at(now, 2, &PRESS));
+        // a fourth copy from a source that already delivered it is a new note
+        assert!(!dedup.is_duplicate_at(now, 1, &PRESS));
+        assert!(dedup.is_duplicate_at(now, 0, &PRESS));
+    }
+
+    #[test]
+    fn keeps_repeats_from_the_same_source() {
+        let mut dedup = dedup();
+        let now = Instant::now();
+        assert!(!dedup.is_duplicate_at(now, 0, &PRESS));
+        assert!(!dedup.is_duplicate_at(now, 0, &PRESS));
+        assert!(dedup.is_duplicate_at(now, 1, &PRESS));
+        assert!(dedup.is_duplicate_at(now, 1, &PRESS));
+    }
+
+    #[test]
+    fn forgets_messages_after_the_window() {
+        let mut dedup = dedup();
+        let now = Instant::now();
+        assert!(!dedup.is_duplicate_at(now, 0, &PRESS));
+        let later = now + Duration::from_millis(DEFAULT_WINDOW_MS + 1);
+        assert!(!dedup.is_duplicate_at(later, 1, &PRESS));
+    }
+
+    #[test]
+    fn compares_releases_and_other_messages() {
+        let mut dedup = dedup();
+        let now = Instant::now();
+        assert!(!dedup.is_duplicate_at(now, 0, &[0x80, 40, 64]));
+        assert!(dedup.is_duplicate_at(now, 1, &[0x90, 40, 0]));
+        assert!(!dedup.is_duplicate_at(now, 0, &[0xB0, 64, 127]));
+        assert!(!dedup.is_duplicate_at(now, 1, &[0xB0, 64, 0]));
+        assert!(dedup.is_duplicate_at(now, 1, &[0xB0, 64, 127]));
+    }
+
+    #[test]
+    fn is_disabled_by_an_empty_window() {
+        let mut dedup = Dedup::new(Duration::from_millis(0));
+        assert!(!dedup.is_duplicate(0, &PRESS));
+        assert!(!dedup.is_duplicate(1, &PRESS));
+    }
+}
@@ -0,0 +1,121 @@
+use std::error::Error;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+const RULE_BLOCK: &str = "block";
+const RULE_ALLOW: &str = "allow";
+
+// looking up the focused application spawns a process, so the
+// result is reused for notes played in quick succession
+const FOCUS_CACHE_DURATION: Duration = Duration::from_millis(500);
+
+// a list of applications that kitara must (not) type into, matched
+// case-insensitively against the focused application's name and window title
+#[derive(Default)]
+pub struct Guard {
+    blocked: Vec<String>,
+    allowed: Vec<String>,
+    focus: Option<(Instant, Option<String>)>,
+    warned: bool,
+}
+
+impl Guard {
+    // loads a csv file with a header and rows of <block|allow>,<application name>
+    pub fn load(csv: String) -> Result<Guard, Box<dyn Error>> {
+        let mut guard = Guard::default();
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        for record in reader.records() {
+            let record = record?;
+            let app = record.get(1).unwrap_or_default().trim().to_lowercase();
+            // an empty name would be contained in every application's name
+            if app.is_empty() {
+                let line = record.position().map_or(0, |p| p.line());
+                return Err(format!("The guard rule on line {} has no application name", line).into());
+            }
+            match record.get(0).unwrap_or_default().trim() {
+                RULE_BLOCK => guard.blocked.push(app),
+                RULE_ALLOW => guard.allowed.push(app),
+                rule => return Err(format!("Unknown guard rule {}, expected block or allow", rule).into()),
+            }
+        }
+        Ok(guard)
+    }
+
+    // returns the reason why output must be suppressed, if it must be
+    pub fn check(&mut self) -> Option<String> {
+        if self.blocked.is_empty() && self.allowed.is_empty() {
+            return None;
+        }
+
+        let focused = self.focused_application();
+        match focused {
+            Some(app) => {
+                let name = app.to_lowercase();
+                if let Some(rule) = self.blocked.iter().find(|b| name.contains(b.as_str())) {
+                    Some(format!("focused application '{}' matches blocked '{}'", app, rule))
+                } else if !self.allowed.is_empty() && !self.allowed.iter().any(|a| name.contains(a.as_str())) {
+                    Some(format!("focused application '{}' is not in the allow list", app))
+                } else {
+                    None
+                }
+            }
+            // without knowing what has focus only an allow list can be enforced
+            None if !self.allowed.is_empty() => Some("focused application is unknown".into()),
+            None => None,
+        }
+    }
+
+    fn focused_application(&mut self) -> Option<String> {
+        if let Some((at, app)) = &self.focus {
+            if at.elapsed() < FOCUS_CACHE_DURATION {
+                return app.clone();
+            }
+        }
+        let app = match query_focused_application() {
+            Ok(app) => Some(app),
+            Err(e) => {
+                // warned about once, since every note would repeat it
+                if !self.warned {
+                    self.warned = true;
+                    warning!("Cannot tell which application has focus ({}), so block rules are not enforced \
+                              and an allow list blocks everything", e);
+                }
+                None
+            }
+        };
+        self.focus = Some((Instant::now(), app.clone()));
+        app
+    }
+}
+
+fn query_focused_application() -> Result<String, String> {
+    let output = if cfg!(target_os = "macos") {
+        Command::new("osascript")
+            .args(["-e", MACOS_FRONTMOST_SCRIPT])
+            .output()
+    } else if cfg!(windows) {
+        return Err("looking it up is not supported on windows".into());
+    } else {
+        Command::new("xdotool")
+            .args(["getactivewindow", "getwindowname"])
+            .output()
+    };
+    let tool = if cfg!(target_os = "macos") { "osascript" } else { "xdotool" };
+    match output {
+        Ok(o) if o.status.success() => Ok(String::from_utf8_lossy(&o.stdout).trim().to_string()),
+        Ok(o) => Err(format!("{} failed - {}", tool, String::from_utf8_lossy(&o.stderr).trim())),
+        Err(e) => Err(format!("{} could not be run - {}", tool, e)),
+    }
+}
+
+// prints the name of the frontmost application followed
+// by the title of its front window when it has one
+const MACOS_FRONTMOST_SCRIPT: &str = "\
+tell application \"System Events\"
+    set frontApp to first application process whose frontmost is true
+    set appName to name of frontApp
+    try
+        return appName & \" - \" & (name of front window of frontApp)
+    end try
+    return appName
+end tell";
@@ -0,0 +1,166 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// the layout that ships with kitara, written out when no other one is chosen
+const DEFAULT_LAYOUT: &str = include_str!("../map.csv");
+
+const CONFIG_FILENAME: &str = "kitara.conf";
+const LAYOUT_FILENAME: &str = "map.csv";
+
+const KEY_DEVICE: &str = "device";
+const KEY_MAP: &str = "map";
+
+// copies the running binary to the user's bin directory and generates a default
+// config, so that afterwards running `kitara` alone connects to the chosen device
+pub fn run(device_names: &str, layout_filepath: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let bin_dir = bin_dir().ok_or("Cannot find the home directory to install into")?;
+    let config_dir = config_dir().ok_or("Cannot find the home directory to install into")?;
+
+    // binary
+    let exe = env::current_exe()?;
+    let installed_exe = bin_dir.join(exe.file_name().unwrap_or_else(|| "kitara".as_ref()));
+    // running the installed binary copies nothing, even when
+    // the home directory is reached through a symlink
+    if !same_file(&exe, &installed_exe) {
+        fs::create_dir_all(&bin_dir)?;
+        fs::copy(&exe, &installed_exe)?;
+    }
+    println!("Installed {}", installed_exe.display());
+
+    // layout: the chosen one replaces the current one, while the embedded
+    // default never overwrites a layout that may have been edited since
+    fs::create_dir_all(&config_dir)?;
+    let layout = config_dir.join(LAYOUT_FILENAME);
+    match layout_filepath {
+        // copying the installed layout onto itself would empty it
+        Some(path) if same_file(Path::new(path), &layout) => println!("Kept layout {}", layout.display()),
+        Some(path) => {
+            fs::copy(path, &layout)?;
+            println!("Installed layout {} as {}", path, layout.display());
+        }
+        None if layout.exists() => println!("Kept existing layout {}", layout.display()),
+        None => {
+            fs::write(&layout, DEFAULT_LAYOUT)?;
+            println!("Installed default layout {}", layout.display());
+        }
+    }
+
+    let config = config_dir.join(CONFIG_FILENAME);
+    fs::write(&config, format!(
+        "# generated by kitara install, used when kitara runs without arguments\n{} = {}\n{} = {}\n",
+        KEY_DEVICE, device_names,
+        KEY_MAP, layout.display()))?;
+    println!("Wrote {}", config.display());
+
+    check_path(&bin_dir);
+    check_permissions();
+    println!("Run kitara to start typing with {}", device_names);
+    Ok(())
+}
+
+// the device names and layout path of the generated default config, if any
+pub fn default_config() -> Option<(String, String)> {
+    let contents = fs::read_to_string(config_dir()?.join(CONFIG_FILENAME)).ok()?;
+    let mut device = None;
+    let mut map = None;
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.splitn(2, '=');
+        match (parts.next().map(str::trim), parts.next().map(str::trim)) {
+            (Some(KEY_DEVICE), Some(value)) => device = Some(value.to_string()),
+            (Some(KEY_MAP), Some(value)) => map = Some(value.to_string()),
+            _ => warning!("Ignoring invalid line in {}: {}", CONFIG_FILENAME, line),
+        }
+    }
+    Some((device?, map?))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" })
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+fn bin_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(|d| PathBuf::from(d).join("Programs").join("kitara"))
+    } else {
+        home_dir().map(|home| home.join(".local").join("bin"))
+    }
+}
+
+fn config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        env::var_os("APPDATA").map(|d| PathBuf::from(d).join("kitara"))
+    } else if cfg!(target_os = "macos") {
+        home_dir().map(|home| home.join("Library").join("Application Support").join("kitara"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home_dir().map(|home| home.join(".config")))
+            .map(|d| d.join("kitara"))
+    }
+}
+
+fn check_path(bin_dir: &Path) {
+    let on_path = env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|p| p == bin_dir));
+    if !on_path {
+        warning!("{} is not in your PATH, add it to run kitara from anywhere", bin_dir.display());
+    }
+}
+
+// typing keys and reading midi both need permissions that
+// the install cannot grant, so it only explains how to get them
+#[cfg(all(unix, not(target_os = "macos")))]
+fn check_permissions() {
+    use std::io::ErrorKind;
+
+    // alsa reads midi from the sequencer device
+    match fs::OpenOptions::new().read(true).write(true).open("/dev/snd/seq") {
+        Ok(_) => println!("MIDI: /dev/snd/seq is accessible"),
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => warning!(
+            "MIDI: /dev/snd/seq is not accessible, add yourself to the audio group \
+             (sudo usermod -aG audio $USER) and log in again"),
+        Err(e) if e.kind() == ErrorKind::NotFound => warning!(
+            "MIDI: /dev/snd/seq does not exist, load the ALSA sequencer (sudo modprobe snd-seq)"),
+        Err(e) => warning!("MIDI: /dev/snd/seq cannot be opened - {}", e),
+    }
+    match crate::backend::missing_display() {
+        None => println!("Keyboard: a display is available"),
+        Some(reason) => warning!("Keyboard: {}, kitara can only type from a graphical session", reason),
+    }
+}
+
+#[cfg(target_os = "macos")]
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn AXIsProcessTrusted() -> bool;
+}
+
+#[cfg(target_os = "macos")]
+fn check_permissions() {
+    // sending key events requires the accessibility permission
+    if unsafe { AXIsProcessTrusted() } {
+        println!("Keyboard: accessibility permission is granted");
+    } else {
+        warning!("Keyboard: allow your terminal in System Settings > Privacy & Security > Accessibility, \
+                  otherwise no keys are typed");
+    }
+}
+
+// windows needs no special permission to read midi or type keys
+#[cfg(windows)]
+fn check_permissions() {}
@@ -1,12 +1,44 @@
 extern crate midir;
 
-use enigo::{Enigo, Key, KeyboardControllable};
+#[macro_use]
+mod console;
+mod assign;
+mod backend;
+mod condition;
+mod corrections;
+mod counters;
+mod dedup;
+mod guard;
+mod install;
+mod saver;
+mod template;
+mod tuning;
+mod typed;
+mod version;
+
+use enigo::Key;
 use std::io::stdin;
+use std::thread;
 use std::error::Error;
+use std::fmt::Display;
+use std::process;
 
-use midir::{MidiInput, Ignore, MidiInputPort};
+use midir::{MidiInput, MidiInputConnection, Ignore, MidiInputPort};
+
+use assign::Pending;
+use backend::Backend;
+use condition::MidiState;
+use corrections::Corrections;
+use counters::Counters;
+use dedup::Dedup;
+use guard::Guard;
+use typed::TypedText;
 
 use std::{env, fs};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use csv::{Error as CsvError, StringRecord};
 
 const MIDI_INPUT_NAME: &str = "kitara-midi-input";
@@ -15,8 +47,9 @@ const MIDI_INPUT_NAME: &str = "kitara-midi-input";
 const NUM_FRETS: usize = 23;
 // number of strings: 6
 const NUM_STRINGS: usize = 6;
-// standard guitar tuning expressed in midi notes: E A D G B E
-const TUNING_NOTES_HIGH_TO_LOW: &'static [i32] = &[64, 59, 55, 50, 45, 40];
+// standard guitar tuning, E A D G B E from high to low, used unless --tuning
+// is given; it is named rather than numbered so that --a4 applies to it too
+const DEFAULT_TUNING: &str = "E4,B3,G3,D3,A2,E2";
 
 // modifier keys
 const SHIFT: &str = "SH";
@@ -35,68 +68,464 @@ const ARROW_UP: &str = "UP";
 const ARROW_RIGHT: &str = "RI";
 const ARROW_DOWN: &str = "DO";
 
+// kitara keys: holding every MO cell of the base layer at once
+// toggles between the insert and the command mode
+const MODE: &str = "MO";
+// toggles auto-correct on and off
+const AUTOCORRECT: &str = "AC";
+
+// punctuation that --auto-space follows with a space
+const AUTO_SPACE_AFTER: &[char] = &['.', ',', ';', ':', '!', '?'];
+
 // midi status
 const STATUS_PRESS: u8 = 9;
 const STATUS_RELEASE: u8 = 8;
+const STATUS_CONTROL_CHANGE: u8 = 11;
+
+// name of the layer defined by the first block of the csv config
+const BASE_LAYER: &str = "base";
+// name of the layer that cells resolve to in command mode
+const COMMAND_LAYER: &str = "command";
+
+const USAGE: &str = "\
+Usage: kitara [options] [<device-name>[,<device-name>...] <path/to/config/csv>]
+       kitara install <device-name>[,<device-name>...] [path/to/config/csv]
+       kitara assign <path/to/config/csv>
+       kitara --version [--verbose] [--check-update]
+
+Options:
+    --layer <name>               only print the mapping chart of this layer
+    --backend <enigo|dry-run>    how keys are typed (detected when not specified)
+    --guard <path/to/csv>        applications to block or allow typing into
+    --prose                      capitalize the first letter of every sentence
+    --auto-space                 type a space after punctuation
+    --corrections <path/to/csv>  dictionary of typos to auto-correct
+    --all-ports                  connect to every matching port of a device
+    --dedup-ms <ms>              window for dropping notes that arrive twice (default 30, 0 disables)
+    --quiet                      only print errors, warnings and the status line
+    --tuning <notes>             tuning of the strings from high to low, as midi notes,
+                                 note names or frequencies (default E4,B3,G3,D3,A2,E2)
+    --a4 <hz>                    reference pitch the instrument is tuned to (default 440)
+    --version                    print the version, with --verbose also the compiled backends and features
+    --check-update               check the github release feed for a newer version";
 
 #[derive(Debug)]
 struct Mapping {
     midi_channels: Vec<u8>,
+    // midi note of each open string, from high to low
+    tuning: Vec<i32>,
+    layers: Vec<Layer>,
+    // keymap positions of the base layer cells that make up the mode chord
+    mode_chord: Vec<usize>,
+}
+
+#[derive(Debug)]
+struct Layer {
+    name: String,
     keymap: Vec<String>,
 }
 
+#[derive(Debug)]
+struct Options {
+    // one or more devices, e.g. the usb and the bluetooth connection of an instrument
+    device_names: Vec<String>,
+    csv_config_filepath: String,
+    // only print the mapping chart of this layer
+    layer: Option<String>,
+    // detected from the environment when not specified
+    backend: Option<Backend>,
+    // csv list of applications to block or allow typing into
+    guard_filepath: Option<String>,
+    // capitalize the first letter of every sentence
+    prose: bool,
+    // type a space after punctuation
+    auto_space: bool,
+    // csv dictionary of typos and their corrections
+    corrections_filepath: Option<String>,
+    // connect to every port matching a device name, not just the first
+    all_ports: bool,
+    dedup_window: Duration,
+    quiet: bool,
+    tuning: Option<String>,
+    a4: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    // cells resolve to the base layer
+    Insert,
+    // cells resolve to the command layer
+    Command,
+}
+
+impl Mode {
+    fn name(self) -> &'static str {
+        match self {
+            Mode::Insert => "insert",
+            Mode::Command => "command",
+        }
+    }
+}
+
+// everything the midi input callback needs to keep between messages
+struct State {
+    counters: Counters,
+    backend: Backend,
+    guard: Guard,
+    mode: Mode,
+    // the key each held cell resolved to when it was pressed, so that
+    // it is released as the same key even if the mode changed meanwhile
+    held: HashMap<usize, String>,
+    typed: TypedText,
+    prose: bool,
+    auto_space: bool,
+    corrections: Corrections,
+    // toggled by AC cells, on whenever there are corrections to apply
+    autocorrect: bool,
+    dedup: Dedup,
+    midi: MidiState,
+    // unmapped notes that were played, for `kitara assign`
+    pending: Pending,
+}
+
 fn main() {
     // parse args
-    let args: Vec<String> = env::args().collect();
-    assert_eq!(args.len(), 3, "Usage: kitara <device-name> <path/to/config/csv>");
-    let device_name = &args[1];
-    let csv_config_filepath = &args[2];
+    let args: Vec<String> = env::args().skip(1).collect();
+    let flag = |name: &str| args.iter().any(|a| a == name);
+    if flag("--version") || flag("--check-update") {
+        if flag("--version") {
+            version::print_version(flag("--verbose"));
+        }
+        if flag("--check-update") {
+            version::check_for_update();
+        }
+        return;
+    }
+    match args.first().map(String::as_str) {
+        Some("assign") => return run_assign(args.into_iter().skip(1).collect()),
+        Some("install") => return run_install(args.into_iter().skip(1).collect()),
+        _ => {}
+    }
+    let options = parse_args(args);
+    console::set_quiet(options.quiet);
 
     // load and eval csv config file
-    let csv = read_file_as_string(csv_config_filepath);
-    match load_fretboard_mapping(csv) {
-        Ok(m) => listen(m, device_name)
-            .expect("Failed to listen to midi device"),
-        Err(e) => println!("Failed to load config - {}", e),
+    let csv = read_file_as_string(&options.csv_config_filepath);
+    // counters are kept next to the config, e.g. map.csv -> map.counters
+    let counters = Counters::load(&Path::new(&options.csv_config_filepath).with_extension("counters"));
+    let backend = match backend::select(options.backend) {
+        Ok(b) => b,
+        Err(e) => fail(e),
+    };
+    let guard = match &options.guard_filepath {
+        Some(path) => match Guard::load(read_file_as_string(path)) {
+            Ok(g) => g,
+            Err(e) => fail(format!("Failed to load guard list - {}", e)),
+        },
+        None => Guard::default(),
+    };
+    let corrections = match &options.corrections_filepath {
+        Some(path) => match Corrections::load(read_file_as_string(path)) {
+            Ok(c) => c,
+            Err(e) => fail(format!("Failed to load corrections - {}", e)),
+        },
+        None => Corrections::default(),
+    };
+    let mut state = State {
+        counters,
+        backend,
+        guard,
+        mode: Mode::Insert,
+        held: HashMap::new(),
+        typed: TypedText::default(),
+        prose: options.prose,
+        auto_space: options.auto_space,
+        autocorrect: !corrections.is_empty(),
+        corrections,
+        dedup: Dedup::new(options.dedup_window),
+        midi: MidiState::new(),
+        pending: Pending::load(&Path::new(&options.csv_config_filepath).with_extension("pending")),
+    };
+    state.counters.save_in_background();
+    state.pending.save_in_background();
+    let tuning = load_tuning(options.tuning.as_deref(), options.a4);
+    match load_fretboard_mapping(csv, tuning) {
+        Ok(m) => if let Err(e) = listen(m, state, &options) {
+            fail(format!("Failed to listen to midi device - {}", e))
+        },
+        Err(e) => fail(format!("Failed to load config - {}", e)),
     };
 }
 
+// reports an error kitara cannot recover from and exits with a failure status
+fn fail<E: Display>(e: E) -> ! {
+    error!("{}", e);
+    process::exit(1)
+}
+
+fn parse_args(args: Vec<String>) -> Options {
+    let mut positional: Vec<String> = vec![];
+    let mut layer = None;
+    let mut backend = None;
+    let mut guard_filepath = None;
+    let mut prose = false;
+    let mut auto_space = false;
+    let mut corrections_filepath = None;
+    let mut all_ports = false;
+    let mut dedup_ms = dedup::DEFAULT_WINDOW_MS;
+    let mut quiet = false;
+    let mut tuning = None;
+    let mut a4 = tuning::STANDARD_A4;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--layer" => layer = Some(option_value(&mut args, &arg)),
+            "--backend" => backend = Some(
+                Backend::parse(&option_value(&mut args, &arg))
+                    .unwrap_or_else(|| usage_error("--backend must be enigo or dry-run"))),
+            "--guard" => guard_filepath = Some(option_value(&mut args, &arg)),
+            "--prose" => prose = true,
+            "--auto-space" => auto_space = true,
+            "--corrections" => corrections_filepath = Some(option_value(&mut args, &arg)),
+            "--all-ports" => all_ports = true,
+            "--quiet" => quiet = true,
+            "--tuning" => tuning = Some(option_value(&mut args, &arg)),
+            "--a4" => a4 = parse_a4(&option_value(&mut args, &arg)),
+            "--dedup-ms" => dedup_ms = option_value(&mut args, &arg)
+                .parse()
+                .unwrap_or_else(|_| usage_error("--dedup-ms must be a number of milliseconds")),
+            _ if arg.starts_with("--") => usage_error(format!("Unknown option {}", arg)),
+            _ => positional.push(arg),
+        }
+    }
+    // without arguments the config generated by `kitara install` is used
+    if positional.is_empty() {
+        if let Some((device_names, csv_config_filepath)) = install::default_config() {
+            positional = vec![device_names, csv_config_filepath];
+        }
+    }
+    if positional.len() != 2 {
+        usage_error("Expected a device name and a config, or a config generated by kitara install");
+    }
+
+    Options {
+        device_names: positional.remove(0)
+            .split(',')
+            .map(str::to_string)
+            .collect(),
+        csv_config_filepath: positional.remove(0),
+        layer,
+        backend,
+        guard_filepath,
+        prose,
+        auto_space,
+        corrections_filepath,
+        all_ports,
+        dedup_window: Duration::from_millis(dedup_ms),
+        quiet,
+        tuning,
+        a4,
+    }
+}
+
+// the pending notes were recorded with their string and fret,
+// so `kitara assign` does not need to know the tuning
+fn run_assign(args: Vec<String>) {
+    if let Some(arg) = args.iter().find(|a| a.starts_with("--")) {
+        usage_error(format!("Unknown option {}", arg));
+    }
+    if args.len() != 1 {
+        usage_error("kitara assign expects the path to a config");
+    }
+
+    let csv_config_filepath = &args[0];
+    let pending_filepath = Path::new(csv_config_filepath).with_extension("pending");
+    if let Err(e) = assign::run(csv_config_filepath, &pending_filepath) {
+        fail(format!("Failed to assign notes - {}", e));
+    }
+}
+
+fn run_install(args: Vec<String>) {
+    if let Some(arg) = args.iter().find(|a| a.starts_with("--")) {
+        usage_error(format!("Unknown option {}", arg));
+    }
+    if args.is_empty() || args.len() > 2 {
+        usage_error("kitara install expects a device name and optionally the path to a config");
+    }
+    if let Err(e) = install::run(&args[0], args.get(1).map(String::as_str)) {
+        fail(format!("Failed to install - {}", e));
+    }
+}
+
+// reports a problem with the arguments along with the usage
+fn usage_error<E: Display>(e: E) -> ! {
+    fail(format!("{}\n{}", e, USAGE))
+}
+
+fn option_value(args: &mut impl Iterator<Item = String>, option: &str) -> String {
+    args.next().unwrap_or_else(|| usage_error(format!("{} expects a value", option)))
+}
+
+fn parse_a4(hz: &str) -> f64 {
+    hz.parse()
+        .ok()
+        .filter(|hz: &f64| hz.is_finite() && *hz > 0.0)
+        .unwrap_or_else(|| usage_error("--a4 must be a positive frequency in Hz"))
+}
+
+fn load_tuning(spec: Option<&str>, a4: f64) -> Vec<i32> {
+    let tuning = tuning::parse(spec.unwrap_or(DEFAULT_TUNING), a4).unwrap_or_else(|e| fail(e));
+    if tuning.len() != NUM_STRINGS {
+        fail(format!("The tuning lists {} strings instead of {}", tuning.len(), NUM_STRINGS));
+    }
+    tuning
+}
+
 fn read_file_as_string(filepath: &str) -> String {
-    return fs::read_to_string(filepath)
-        .expect(format!("Failed to read file with path {}", filepath).as_ref());
+    fs::read_to_string(filepath)
+        .unwrap_or_else(|e| fail(format!("Failed to read file with path {} - {}", filepath, e)))
 }
 
-fn load_fretboard_mapping(csv: String) -> Result<Mapping, CsvError> {
+// the csv config is made of one or more blocks of NUM_STRINGS rows;
+// the first block is the base layer and every following block is
+// introduced by a header row whose first column is the layer's name, e.g.
+// command,0,1,2,...,22
+fn load_fretboard_mapping(csv: String, tuning: Vec<i32>) -> Result<Mapping, Box<dyn Error>> {
     let mut reader = csv::Reader::from_reader(csv.as_bytes());
-    let rows: Vec<StringRecord> = reader.records()
-        .map(Result::unwrap)
+    let records: Vec<StringRecord> = reader.records()
+        .collect::<Result<_, CsvError>>()?;
+
+    // group the string rows of every layer under the layer name
+    let mut blocks: Vec<(String, Vec<StringRecord>)> = vec![(BASE_LAYER.into(), vec![])];
+    for record in records {
+        match record[0].parse::<u8>() {
+            Ok(_) => blocks.last_mut().unwrap().1.push(record),
+            Err(_) => blocks.push((record[0].into(), vec![])),
+        }
+    }
+
+    let midi_channels: Vec<u8> = blocks[0].1
+        .iter()
+        .map(|row| row[0].parse::<u8>().unwrap())
         .collect();
 
-    let mut fretboard: Vec<String> = vec!["".into(); NUM_FRETS * NUM_STRINGS];
-    for i in 0..NUM_STRINGS {
-        for j in 0..NUM_FRETS {
-            fretboard[i * NUM_FRETS + j] = rows[i][j + 1].into();
+    let mut layers = vec![];
+    for (name, rows) in blocks {
+        if rows.len() != NUM_STRINGS {
+            return Err(format!("Layer {} has {} strings instead of {}", name, rows.len(), NUM_STRINGS).into());
         }
+        // every layer maps the same strings, so it must list
+        // the same midi channels in the same order as the base layer
+        for (row, channel) in rows.iter().zip(midi_channels.iter()) {
+            if row[0].parse::<u8>().unwrap() != *channel {
+                return Err(format!("Layer {} lists channel {} where {} was expected", name, &row[0], channel).into());
+            }
+        }
+
+        let mut fretboard: Vec<String> = vec!["".into(); NUM_FRETS * NUM_STRINGS];
+        for i in 0..NUM_STRINGS {
+            for j in 0..NUM_FRETS {
+                fretboard[i * NUM_FRETS + j] = rows[i][j + 1].into();
+            }
+        }
+        // conditions are checked now rather than when the cell is played
+        for cell in &fretboard {
+            if let Some((c, _)) = condition::split(cell) {
+                condition::validate(c).map_err(|e| format!("Layer {} - {}", name, e))?;
+            }
+        }
+        layers.push(Layer { name, keymap: fretboard });
     }
 
-    return Result::Ok(Mapping {
-        midi_channels: rows
-            .iter()
-            .map(|row| row[0].parse::<u8>().unwrap())
-            .collect(),
-        keymap: fretboard,
-    });
+    let mode_chord = layers[0].keymap
+        .iter()
+        .enumerate()
+        .filter(|(_, key)| key.as_str() == MODE)
+        .map(|(position, _)| position)
+        .collect();
+
+    Result::Ok(Mapping {
+        midi_channels,
+        tuning,
+        layers,
+        mode_chord,
+    })
 }
 
-fn listen(_mapping: Mapping, _midi_device_name: &str) -> Result<(), Box<dyn Error>> {
-    print_keyboard_mapping(&_mapping);
+fn listen(_mapping: Mapping, state: State, options: &Options) -> Result<(), Box<dyn Error>> {
+    if !console::is_quiet() {
+        print_keyboard_mapping(&_mapping, options.layer.as_deref());
+    }
 
-    let mut midi_in = MidiInput::new(MIDI_INPUT_NAME)?;
-    midi_in.ignore(Ignore::None);
+    let backend = state.backend;
+
+    // a device may deliver its notes on any of its ports, so either
+    // the first or all of the ports matching each device name are used
+    let midi_in = MidiInput::new(MIDI_INPUT_NAME)?;
+    let mut port_names: Vec<String> = vec![];
+    for _midi_device_name in &options.device_names {
+        let names = matching_ports(&midi_in, _midi_device_name)
+            .iter()
+            .map(|p| midi_in.port_name(p))
+            .collect::<Result<Vec<String>, _>>()?;
+        if names.is_empty() {
+            return Err(format!("No input port found matching {}", _midi_device_name).into());
+        }
+        let count = if options.all_ports { names.len() } else { 1 };
+        for name in names.into_iter().take(count) {
+            // a port may match more than one device name
+            if !port_names.contains(&name) {
+                port_names.push(name);
+            }
+        }
+    }
 
-    // filter out all midi in ports that match
-    // the specified device name
-    let matching_ports = midi_in.ports()
+    // every connection calls back on its own thread,
+    // so the mapping and the state are shared among them
+    let mapping = Arc::new(_mapping);
+    let state = Arc::new(Mutex::new(state));
+
+    let mut connections = vec![];
+    for (source, port_name) in port_names.iter().enumerate() {
+        connections.push(connect(source, port_name, mapping.clone(), state.clone())?);
+        info!("Successfully connected to MIDI Device: {}", port_name);
+    }
+    info!("Typing with the {} backend", backend.name());
+
+    let quoted_port_names: Vec<String> = port_names.iter().map(|n| format!("{:?}", n)).collect();
+    console::status(&[
+        ("status", "listening".into()),
+        ("version", version::VERSION.into()),
+        ("backend", backend.name().into()),
+        ("ports", quoted_port_names.join(",")),
+    ]);
+
+    // kitara does no work of its own between notes: the midi backend wakes
+    // the connection callbacks when a message arrives, while the main thread only
+    // waits for enter to quit. Without a terminal (e.g. when run as a service)
+    // stdin ends right away, so the main thread is parked until killed instead
+    if stdin().read_line(&mut String::new())? == 0 {
+        loop {
+            thread::park();
+        }
+    }
+
+    // no more notes arrive once the connections are closed,
+    // so whatever they recorded can be written out for good
+    for connection in connections {
+        connection.close();
+    }
+    let mut state = state.lock().unwrap();
+    state.counters.finish();
+    state.pending.finish();
+    Ok(())
+}
+
+// filter out all midi in ports that match
+// the specified device name
+fn matching_ports(midi_in: &MidiInput, _midi_device_name: &str) -> Vec<MidiInputPort> {
+    midi_in.ports()
         .into_iter()
         .filter(|p|
             midi_in
@@ -104,90 +533,175 @@ fn listen(_mapping: Mapping, _midi_device_name: &str) -> Result<(), Box<dyn Erro
                 .unwrap()
                 .to_lowercase()
                 .contains(&_midi_device_name.to_lowercase())
-        ).collect::<Vec<MidiInputPort>>();
+        ).collect::<Vec<MidiInputPort>>()
+}
 
-    // select the first port from list of matching ports
-    let in_port = match matching_ports.len() {
-        0 => return Err(format!("No input port found matching {}", _midi_device_name).into()),
-        _ => &matching_ports[0]
-    };
+// connecting consumes the midi input, so every port is looked up
+// with a new one; source identifies the connection to dedup
+fn connect(
+    source: usize,
+    port_name: &str,
+    mapping: Arc<Mapping>,
+    state: Arc<Mutex<State>>,
+) -> Result<MidiInputConnection<()>, Box<dyn Error>> {
+    let mut midi_in = MidiInput::new(MIDI_INPUT_NAME)?;
+    midi_in.ignore(Ignore::None);
 
-    // get device name before it is moved below
-    let full_device_name = midi_in.port_name(in_port)?;
-
-    let _conn_in = midi_in.connect(in_port, MIDI_INPUT_NAME, move |_, message, _| {
-        if message.len() > 1 {
-            // MIDI channel is encoded in the lower four bits
-            // of the first byte of the message
-            // one is added because midi channels are zero-based
-            let channel = (message[0] & 0x0F) + 1u8;
-
-            // MIDI Status is encoded in the higher four bits
-            // of the first byte of the message
-            let status = message[0] >> 4;
-
-            // MIDI Note is encoded on the second byte of the message
-            let note = i32::from(message[1]);
-
-            // Let's only deal with Status 8 and 9 for our purpose
-            if status == STATUS_PRESS || status == STATUS_RELEASE {
-                // execute typing only if the message's MIDI channel
-                // matches one of the channels from the mapping struct
-                match _mapping.midi_channels.iter().position(|&x| x == channel) {
-                    Some(gtr_string) => handle_robo_typing(
-                        &_mapping,
-                        channel,
-                        status,
-                        gtr_string,
-                        note),
-                    None => println!("Failed mapping channel {}", channel),
-                }
-            }
+    let in_port = midi_in.ports()
+        .into_iter()
+        .find(|p| midi_in.port_name(p).is_ok_and(|name| name == port_name))
+        .ok_or(format!("Input port {} is gone", port_name))?;
+
+    let connection = midi_in.connect(&in_port, MIDI_INPUT_NAME, move |_, message, _| {
+        let mut state = state.lock().unwrap();
+        if !state.dedup.is_duplicate(source, message) {
+            handle_message(&mapping, &mut state, message);
         }
     }, ())?;
-    println!("Successfully connected to MIDI Device: {}", full_device_name);
+    Ok(connection)
+}
 
-    stdin().read_line(&mut String::new())?;
-    Ok(())
+fn handle_message(_mapping: &Mapping, state: &mut State, message: &[u8]) {
+    if message.len() > 1 {
+        // MIDI channel is encoded in the lower four bits
+        // of the first byte of the message
+        // one is added because midi channels are zero-based
+        let channel = (message[0] & 0x0F) + 1u8;
+
+        // MIDI Status is encoded in the higher four bits
+        // of the first byte of the message
+        // a note on with velocity 0 is how many controllers release a note
+        let status = match message[0] >> 4 {
+            STATUS_PRESS if message.get(2) == Some(&0) => STATUS_RELEASE,
+            status => status,
+        };
+
+        // MIDI Note is encoded on the second byte of the message
+        let note = i32::from(message[1]);
+
+        // controllers and held notes are tracked for cell conditions
+        match status {
+            STATUS_PRESS => state.midi.note_on(channel, message[1]),
+            STATUS_RELEASE => state.midi.note_off(channel, message[1]),
+            STATUS_CONTROL_CHANGE if message.len() > 2 => state.midi.control_change(message[1], message[2]),
+            _ => {}
+        }
+
+        // Let's only deal with Status 8 and 9 for typing
+        if status == STATUS_PRESS || status == STATUS_RELEASE {
+            // execute typing only if the message's MIDI channel
+            // matches one of the channels from the mapping struct
+            match _mapping.midi_channels.iter().position(|&x| x == channel) {
+                Some(gtr_string) => handle_robo_typing(
+                    _mapping,
+                    state,
+                    channel,
+                    status,
+                    gtr_string,
+                    note),
+                None => {
+                    info!("Failed mapping channel {}", channel);
+                    if status == STATUS_PRESS {
+                        state.pending.record(channel, note, None);
+                    }
+                }
+            }
+        }
+    }
 }
 
-fn handle_robo_typing(_mapping: &Mapping, channel: u8, status: u8, gtr_string: usize, note: i32) {
+fn handle_robo_typing(_mapping: &Mapping, state: &mut State, channel: u8, status: u8, gtr_string: usize, note: i32) {
     // guitar fret is derived by subtracting the tuning note for
     // the string played from the current midi note played
-    let gtr_fret = note - TUNING_NOTES_HIGH_TO_LOW[gtr_string];
+    let gtr_fret = note - _mapping.tuning[gtr_string];
+    if gtr_fret < 0 || gtr_fret as usize >= NUM_FRETS {
+        if status == STATUS_PRESS {
+            info!("Note {} on channel {} is outside of the fretboard (string={}, fret={})",
+                  note, channel, gtr_string, gtr_fret);
+            state.pending.record(channel, note, Some((gtr_string, gtr_fret)));
+        }
+        return;
+    }
     // because the entire fretboard is encoded into a 1-dimensional vector
     // the right position for the string/fret needs to be calculated
     let keymap_position = gtr_string * NUM_FRETS + (gtr_fret as usize);
-    // the key represents the keyboard key that will be invoked in this command
-    let key = &_mapping.keymap[keymap_position][..];
+    // the key represents the keyboard key that will be invoked in this command;
+    // it is resolved on press and remembered until the note is released
+    let key = if status == STATUS_PRESS {
+        let key = resolve_key(_mapping, state, keymap_position);
+        state.held.insert(keymap_position, key.clone());
+        key
+    } else {
+        state.held
+            .remove(&keymap_position)
+            .unwrap_or_else(|| resolve_key(_mapping, state, keymap_position))
+    };
+    let key = &key[..];
+    // empty cells of the base layer can be mapped later with `kitara assign`
+    if status == STATUS_PRESS && state.mode == Mode::Insert && _mapping.layers[0].keymap[keymap_position].is_empty() {
+        state.pending.record(channel, note, Some((gtr_string, gtr_fret)));
+    }
+    // releases always go through so that no key is left held down
+    if status == STATUS_PRESS && !key.is_empty() && key != MODE && key != AUTOCORRECT {
+        if let Some(reason) = state.guard.check() {
+            warning!("Suppressed key={} - {}", key, reason);
+            return;
+        }
+    }
+    // a delimiter ends the word that was just typed, so it is corrected
+    // before the delimiter itself is typed
+    if status == STATUS_PRESS && state.autocorrect && is_delimiter(key) {
+        autocorrect_last_word(state);
+    }
     match key {
+        // kitara keys
+        MODE => {
+            let chord_held = _mapping.mode_chord
+                .iter()
+                .all(|position| state.held.contains_key(position));
+            if status == STATUS_PRESS && chord_held {
+                toggle_mode(_mapping, state);
+            }
+        }
+        AUTOCORRECT => {
+            if status == STATUS_PRESS {
+                state.autocorrect = !state.autocorrect;
+                info!("Auto-correct {}", if state.autocorrect { "on" } else { "off" });
+            }
+        }
         // modifier keys
-        SHIFT => press_release_key(status, Key::Shift),
-        CTRL => press_release_key(status, Key::Control),
-        ALT => press_release_key(status, Key::Alt),
-        CMD => press_release_key(status, Key::Meta),
+        SHIFT => press_release_key(state.backend, status, Key::Shift),
+        CTRL => press_release_key(state.backend, status, Key::Control),
+        ALT => press_release_key(state.backend, status, Key::Alt),
+        CMD => press_release_key(state.backend, status, Key::Meta),
         // whitespace
-        SPACE => click_key(status, Key::Space),
-        TAB => click_key(status, Key::Tab),
-        BACKSPACE => click_key(status, Key::Backspace),
-        ENTER => click_key(status, Key::Return),
+        SPACE => type_char(state, status, ' '),
+        TAB => click_key(state.backend, status, Key::Tab),
+        BACKSPACE => click_key(state.backend, status, Key::Backspace),
+        ENTER => click_key(state.backend, status, Key::Return),
         // control keys
-        ESCAPE => click_key(status, Key::Escape),
-        ARROW_LEFT => click_key(status, Key::LeftArrow),
-        ARROW_UP => click_key(status, Key::UpArrow),
-        ARROW_RIGHT => click_key(status, Key::RightArrow),
-        ARROW_DOWN => click_key(status, Key::DownArrow),
+        ESCAPE => click_key(state.backend, status, Key::Escape),
+        ARROW_LEFT => click_key(state.backend, status, Key::LeftArrow),
+        ARROW_UP => click_key(state.backend, status, Key::UpArrow),
+        ARROW_RIGHT => click_key(state.backend, status, Key::RightArrow),
+        ARROW_DOWN => click_key(state.backend, status, Key::DownArrow),
+        // text macros (any cell with more than one character)
+        _ if key.chars().count() > 1 => type_text(state, status, key),
         // all other characters
         _ => {
             if !key.is_empty() {
                 let ch = key.chars().next().unwrap();
-                click_key(status, Key::Layout(ch));
+                type_char(state, status, ch);
             }
         }
     }
+    if status == STATUS_PRESS {
+        track_typed_key(&mut state.typed, key);
+    }
 
-    println!(
-        "string={}, fret={}, channel={}, note={}, key={}, action={}",
+    info!(
+        "[{}] string={}, fret={}, channel={}, note={}, key={}, action={}",
+        state.mode.name(),
         gtr_string,
         gtr_fret,
         channel,
@@ -204,9 +718,58 @@ fn handle_robo_typing(_mapping: &Mapping, channel: u8, status: u8, gtr_string: u
     );
 }
 
-fn print_keyboard_mapping(_mapping: &Mapping) {
+// mode chord cells always belong to the chord, in any mode,
+// and cells whose condition does not hold are not mapped to any key
+fn resolve_key(_mapping: &Mapping, state: &State, keymap_position: usize) -> String {
+    if _mapping.mode_chord.contains(&keymap_position) {
+        return MODE.into();
+    }
+    let layer = match state.mode {
+        Mode::Insert => &_mapping.layers[0],
+        Mode::Command => _mapping.layers
+            .iter()
+            .find(|l| l.name == COMMAND_LAYER)
+            .unwrap_or(&_mapping.layers[0]),
+    };
+    let cell = &layer.keymap[keymap_position];
+    match condition::split(cell) {
+        Some((c, key)) if condition::evaluate(c, &state.midi) => key.into(),
+        Some(_) => "".into(),
+        None => cell.clone(),
+    }
+}
+
+fn toggle_mode(_mapping: &Mapping, state: &mut State) {
+    if state.mode == Mode::Insert && !_mapping.layers.iter().any(|l| l.name == COMMAND_LAYER) {
+        warning!("Cannot switch to command mode: the config has no {} layer", COMMAND_LAYER);
+        return;
+    }
+    state.mode = match state.mode {
+        Mode::Insert => Mode::Command,
+        Mode::Command => Mode::Insert,
+    };
+    info!("Switched to {} mode", state.mode.name());
+}
+
+fn print_keyboard_mapping(_mapping: &Mapping, layer_name: Option<&str>) {
+    let tuning: Vec<String> = _mapping.tuning.iter().map(i32::to_string).collect();
+    println!("\nTuning (high to low): {}", tuning.join(" "));
+
+    let layers: Vec<&Layer> = _mapping.layers
+        .iter()
+        .filter(|l| layer_name.is_none_or(|name| l.name == name))
+        .collect();
+    if layers.is_empty() {
+        warning!("No layer named {}", layer_name.unwrap_or_default());
+    }
+    for layer in layers {
+        print_layer(&_mapping.midi_channels, layer);
+    }
+}
+
+fn print_layer(midi_channels: &[u8], layer: &Layer) {
     //print header
-    println!("\nKeyboard Mapping:");
+    println!("\nKeyboard Mapping (layer: {}):", layer.name);
     for _j in 0..NUM_FRETS {
         print!("{}\t", _j)
     }
@@ -219,28 +782,99 @@ fn print_keyboard_mapping(_mapping: &Mapping) {
     println!();
 
     //print mapping for each string
-    for i in 0..NUM_STRINGS {
-        print!("{}|", &_mapping.midi_channels[i]);
+    for (i, channel) in midi_channels.iter().enumerate() {
+        print!("{}|", channel);
         for j in 0..NUM_FRETS {
-            print!("{}\t", &_mapping.keymap[i * NUM_FRETS + j]);
+            print!("{}\t", &layer.keymap[i * NUM_FRETS + j]);
         }
         println!();
     }
     println!();
 }
 
-fn press_release_key(status: u8, key: Key) {
-    let mut enigo = Enigo::new();
+fn press_release_key(backend: Backend, status: u8, key: Key) {
     if status == STATUS_PRESS {
-        enigo.key_down(key);
+        backend.key_down(key);
     } else {
-        enigo.key_up(key);
+        backend.key_up(key);
+    }
+}
+
+fn click_key(backend: Backend, status: u8, key: Key) {
+    if status == STATUS_PRESS {
+        backend.key_click(key);
     }
 }
 
-fn click_key(status: u8, key: Key) {
-    let mut enigo = Enigo::new();
+fn type_text(state: &mut State, status: u8, text: &str) {
+    // placeholders are only expanded when the macro is actually
+    // typed so that releases do not advance any counters
     if status == STATUS_PRESS {
-        enigo.key_click(key);
+        let expanded = template::expand(text, &mut state.counters);
+        state.backend.key_sequence(&expanded);
+        state.typed.push_str(&expanded);
+    }
+}
+
+fn type_char(state: &mut State, status: u8, ch: char) {
+    if status != STATUS_PRESS {
+        return;
+    }
+    // a space right after an automatic one would be a double space
+    if ch == ' ' && state.auto_space && state.typed.take_auto_space() {
+        return;
+    }
+    let ch = if state.prose && state.typed.at_sentence_start() {
+        ch.to_uppercase().next().unwrap_or(ch)
+    } else {
+        ch
+    };
+    state.backend.key_click(match ch {
+        ' ' => Key::Space,
+        _ => Key::Layout(ch),
+    });
+    state.typed.push(ch);
+
+    if state.auto_space && AUTO_SPACE_AFTER.contains(&ch) {
+        state.backend.key_click(Key::Space);
+        state.typed.push_auto_space();
+    }
+}
+
+fn is_delimiter(key: &str) -> bool {
+    match key {
+        SPACE | TAB | ENTER => true,
+        _ => {
+            let mut chars = key.chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) => ch.is_ascii_punctuation() && ch != '\'' && ch != '-',
+                _ => false,
+            }
+        }
+    }
+}
+
+// replaces a known typo right before the cursor with its correction
+fn autocorrect_last_word(state: &mut State) {
+    let typo = state.typed.last_word().to_string();
+    if let Some(fix) = state.corrections.fix(&typo) {
+        for _ in typo.chars() {
+            state.backend.key_click(Key::Backspace);
+        }
+        state.backend.key_sequence(&fix);
+        state.typed.replace_last_word(&fix);
+        info!("Corrected {} -> {}", typo, fix);
+    }
+}
+
+// keeps the typed text model in sync with keys that are not typed as characters
+fn track_typed_key(typed: &mut TypedText, key: &str) {
+    match key {
+        TAB => typed.push('\t'),
+        ENTER => typed.push('\n'),
+        BACKSPACE => typed.backspace(),
+        // the cursor moves or a shortcut runs, so what precedes the cursor is unknown
+        ESCAPE | ARROW_LEFT | ARROW_UP | ARROW_RIGHT | ARROW_DOWN | CTRL | ALT | CMD => typed.reset(),
+        _ => {}
     }
 }
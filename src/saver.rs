@@ -0,0 +1,70 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+// writes one of the state files kept next to the config (counters, pending
+// notes); while kitara runs this happens on a thread of its own, so that no
+// file is written from the midi callbacks while they hold the state lock
+pub struct Saver {
+    path: PathBuf,
+    // what the file holds, for error messages
+    contents: &'static str,
+    writer: Option<(Sender<Vec<u8>>, JoinHandle<()>)>,
+}
+
+impl Saver {
+    pub fn new(path: &Path, contents: &'static str) -> Saver {
+        Saver { path: path.to_path_buf(), contents, writer: None }
+    }
+
+    // from now on the file is written by a thread that only wakes
+    // up when there is something to save and skips outdated snapshots
+    pub fn save_in_background(&mut self) {
+        let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+        let path = self.path.clone();
+        let contents = self.contents;
+        let handle = thread::spawn(move || {
+            while let Ok(mut bytes) = receiver.recv() {
+                if let Some(latest) = receiver.try_iter().last() {
+                    bytes = latest;
+                }
+                if let Err(e) = write(&path, &bytes) {
+                    error!("Failed to save {} to {} - {}", contents, path.display(), e);
+                }
+            }
+        });
+        self.writer = Some((sender, handle));
+    }
+
+    pub fn save(&self, bytes: Result<Vec<u8>, String>) {
+        let saved = bytes.and_then(|bytes| match &self.writer {
+            Some((writer, _)) => writer.send(bytes).map_err(|e| e.to_string()),
+            None => write(&self.path, &bytes).map_err(|e| e.to_string()),
+        });
+        if let Err(e) = saved {
+            error!("Failed to save {} to {} - {}", self.contents, self.path.display(), e);
+        }
+    }
+
+    // waits for the snapshots still queued to be written, as
+    // they would be lost if kitara exited in the meantime
+    pub fn finish(&mut self) {
+        if let Some((sender, handle)) = self.writer.take() {
+            drop(sender);
+            let _ = handle.join();
+        }
+    }
+}
+
+// the file is replaced in one go, so that quitting in the middle
+// of a save does not leave it truncated, e.g. map.counters is
+// written as map.counters.tmp first
+fn write(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut temporary = OsString::from(path.as_os_str());
+    temporary.push(".tmp");
+    fs::write(&temporary, bytes)?;
+    fs::rename(&temporary, path)
+}
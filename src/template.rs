@@ -0,0 +1,251 @@
+use crate::counters::Counters;
+use std::env;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// placeholders are written as {{name}} or {{name:argument}}
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+// expands every known placeholder found in the text of a macro cell;
+// unknown or unterminated placeholders are typed verbatim
+pub fn expand(text: &str, counters: &mut Counters) -> String {
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(OPEN) {
+        expanded.push_str(&rest[..start]);
+        let after_open = &rest[start + OPEN.len()..];
+        match after_open.find(CLOSE) {
+            Some(end) => {
+                let placeholder = &after_open[..end];
+                match expand_placeholder(placeholder, counters) {
+                    Some(value) => expanded.push_str(&value),
+                    None => {
+                        expanded.push_str(OPEN);
+                        expanded.push_str(placeholder);
+                        expanded.push_str(CLOSE);
+                    }
+                }
+                rest = &after_open[end + CLOSE.len()..];
+            }
+            None => {
+                expanded.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+fn expand_placeholder(placeholder: &str, counters: &mut Counters) -> Option<String> {
+    // the name is everything up to the first colon,
+    // the argument (if any) is everything after it
+    let (name, argument) = match placeholder.find(':') {
+        Some(i) => (&placeholder[..i], Some(&placeholder[i + 1..])),
+        None => (placeholder, None),
+    };
+    match name {
+        "date" => Some(format_date(argument.unwrap_or(DEFAULT_DATE_FORMAT))),
+        "clipboard" => Some(read_clipboard()),
+        "env" => argument.map(|var| env::var(var).unwrap_or_default()),
+        "counter" => argument.map(|counter| expand_counter(counter, counters)),
+        _ => None,
+    }
+}
+
+// {{counter:name}} increments the counter and inserts the new value,
+// {{counter:name:peek}} inserts the current value as is and
+// {{counter:name:reset}} sets it back to zero without inserting anything
+fn expand_counter(counter: &str, counters: &mut Counters) -> String {
+    let mut parts = counter.splitn(2, ':');
+    let name = parts.next().unwrap_or_default();
+    match parts.next() {
+        Some("peek") => counters.peek(name).to_string(),
+        Some("reset") => {
+            counters.reset(name);
+            String::new()
+        }
+        _ => counters.take(name).to_string(),
+    }
+}
+
+// formats the current local time using a subset of strftime specifiers:
+// %Y %y %m %d %H %M %S and %% (anything else is copied as is)
+fn format_date(format: &str) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let local = now + utc_offset_seconds(now);
+
+    let days = local.div_euclid(86_400);
+    let seconds_of_day = local.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    let (hour, minute, second) = (seconds_of_day / 3600, seconds_of_day / 60 % 60, seconds_of_day % 60);
+
+    let mut formatted = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            formatted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => formatted.push_str(&format!("{:04}", year)),
+            Some('y') => formatted.push_str(&format!("{:02}", year.rem_euclid(100))),
+            Some('m') => formatted.push_str(&format!("{:02}", month)),
+            Some('d') => formatted.push_str(&format!("{:02}", day)),
+            Some('H') => formatted.push_str(&format!("{:02}", hour)),
+            Some('M') => formatted.push_str(&format!("{:02}", minute)),
+            Some('S') => formatted.push_str(&format!("{:02}", second)),
+            Some('%') => formatted.push('%'),
+            Some(other) => {
+                formatted.push('%');
+                formatted.push(other);
+            }
+            None => formatted.push('%'),
+        }
+    }
+    formatted
+}
+
+// converts days since the unix epoch into a (year, month, day) date
+// see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(unix)]
+fn utc_offset_seconds(timestamp: i64) -> i64 {
+    let time = timestamp as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    // localtime_r is the thread-safe variant and fills in the offset from UTC
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return 0;
+    }
+    tm.tm_gmtoff as i64
+}
+
+// TIME_ZONE_INFORMATION, of which only the biases are needed
+#[cfg(windows)]
+#[repr(C)]
+struct TimeZoneInformation {
+    bias: i32,
+    standard_name: [u16; 32],
+    standard_date: [u16; 8],
+    standard_bias: i32,
+    daylight_name: [u16; 32],
+    daylight_date: [u16; 8],
+    daylight_bias: i32,
+}
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetTimeZoneInformation(info: *mut TimeZoneInformation) -> u32;
+}
+
+// the offset currently in effect, which is the one of the timestamp as it is always now
+#[cfg(windows)]
+fn utc_offset_seconds(_timestamp: i64) -> i64 {
+    let mut info: TimeZoneInformation = unsafe { std::mem::zeroed() };
+    // the biases are the minutes to add to the local time to get utc
+    let bias = match unsafe { GetTimeZoneInformation(&mut info) } {
+        // TIME_ZONE_ID_UNKNOWN, the zone has no daylight saving time
+        0 => info.bias,
+        // TIME_ZONE_ID_STANDARD
+        1 => info.bias + info.standard_bias,
+        // TIME_ZONE_ID_DAYLIGHT
+        2 => info.bias + info.daylight_bias,
+        _ => return 0,
+    };
+    -i64::from(bias) * 60
+}
+
+#[cfg(not(any(unix, windows)))]
+fn utc_offset_seconds(_timestamp: i64) -> i64 {
+    0
+}
+
+// there is no portable clipboard api in std, so the
+// platform's own command line clipboard tool is used
+fn read_clipboard() -> String {
+    let output = if cfg!(target_os = "macos") {
+        Command::new("pbpaste").output()
+    } else if cfg!(windows) {
+        Command::new("powershell")
+            .args(["-NoProfile", "-Command", "Get-Clipboard"])
+            .output()
+    } else if env::var_os("WAYLAND_DISPLAY").is_some() {
+        Command::new("wl-paste").arg("--no-newline").output()
+    } else {
+        Command::new("xclip").args(["-selection", "clipboard", "-o"]).output()
+    };
+
+    match output {
+        Ok(o) if o.status.success() => {
+            let text = String::from_utf8_lossy(&o.stdout).into_owned();
+            // Get-Clipboard always terminates its output with a newline
+            if cfg!(windows) {
+                text.trim_end_matches(&['\r', '\n'][..]).to_string()
+            } else {
+                text
+            }
+        }
+        _ => {
+            warning!("Failed to read clipboard");
+            String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // counters are saved on every change, so each test gets a file of its own
+    fn counters(test: &str) -> (Counters, std::path::PathBuf) {
+        let path = env::temp_dir().join(format!("kitara-{}-{}.counters", test, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        (Counters::load(&path), path)
+    }
+
+    #[test]
+    fn converts_days_to_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19_723), (2024, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn types_unknown_and_unterminated_placeholders_verbatim() {
+        let (mut counters, path) = counters("verbatim");
+        assert_eq!(expand("plain text", &mut counters), "plain text");
+        assert_eq!(expand("a {{b", &mut counters), "a {{b");
+        assert_eq!(expand("{{nope}} and {{", &mut counters), "{{nope}} and {{");
+        assert_eq!(expand("{{env}}", &mut counters), "{{env}}");
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn expands_counters() {
+        let (mut counters, path) = counters("counters");
+        assert_eq!(expand("#{{counter:n}} #{{counter:n}}", &mut counters), "#1 #2");
+        assert_eq!(expand("{{counter:n:peek}}", &mut counters), "2");
+        assert_eq!(expand("{{counter:n:reset}}{{counter:n}}", &mut counters), "1");
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,98 @@
+// the reference pitch midi synths assume, A4 = note 69 = 440 Hz
+pub const STANDARD_A4: f64 = 440.0;
+const A4_NOTE: f64 = 69.0;
+
+// parses a comma separated list of string tunings, each of which is a midi
+// note number (40), a note in scientific pitch notation (E2, F#3, Bb2) or a
+// frequency (82.41Hz). The synth detects pitch against STANDARD_A4, so notes
+// are shifted when the instrument is tuned to another reference (e.g. A4=415)
+pub fn parse(spec: &str, a4: f64) -> Result<Vec<i32>, String> {
+    spec.split(',').map(|s| parse_note(s.trim(), a4)).collect()
+}
+
+fn parse_note(note: &str, a4: f64) -> Result<i32, String> {
+    let invalid = || format!("Invalid tuning {}, expected e.g. 40, E2 or 82.41Hz", note);
+    let lowercase = note.to_lowercase();
+
+    let midi_note = if let Some(hz) = lowercase.strip_suffix("hz") {
+        // a frequency is what the synth hears, whatever the reference
+        let frequency: f64 = hz.trim().parse().map_err(|_| invalid())?;
+        // nan and infinity parse as floats too
+        if !frequency.is_finite() || frequency <= 0.0 {
+            return Err(invalid());
+        }
+        frequency_to_note(frequency)
+    } else if let Ok(number) = note.parse::<i32>() {
+        number
+    } else {
+        let named = parse_scientific_pitch(note).ok_or_else(invalid)?;
+        // the frequency of the named note at the instrument's reference pitch
+        let frequency = a4 * 2f64.powf((f64::from(named) - A4_NOTE) / 12.0);
+        frequency_to_note(frequency)
+    };
+
+    if (0..128).contains(&midi_note) {
+        Ok(midi_note)
+    } else {
+        Err(format!("Tuning {} is outside of the midi note range", note))
+    }
+}
+
+fn frequency_to_note(frequency: f64) -> i32 {
+    (A4_NOTE + 12.0 * (frequency / STANDARD_A4).log2()).round() as i32
+}
+
+// C4 is midi note 60, so C-1 is note 0
+fn parse_scientific_pitch(note: &str) -> Option<i32> {
+    let mut chars = note.chars();
+    let semitone = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let (accidental, octave) = match rest.chars().next()? {
+        '#' => (1, &rest[1..]),
+        'b' => (-1, &rest[1..]),
+        _ => (0, rest),
+    };
+    let octave: i32 = octave.parse().ok()?;
+    Some((octave + 1) * 12 + semitone + accidental)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_notes_names_and_frequencies() {
+        assert_eq!(parse("40", STANDARD_A4), Ok(vec![40]));
+        assert_eq!(parse("E2", STANDARD_A4), Ok(vec![40]));
+        assert_eq!(parse("82.41Hz", STANDARD_A4), Ok(vec![40]));
+        assert_eq!(parse("Bb2, A#2", STANDARD_A4), Ok(vec![46, 46]));
+        assert_eq!(parse("C-1", STANDARD_A4), Ok(vec![0]));
+    }
+
+    #[test]
+    fn shifts_named_notes_to_the_reference_pitch() {
+        assert_eq!(parse("E2", 415.0), Ok(vec![39]));
+        assert_eq!(parse("E2", 432.0), Ok(vec![40]));
+        // frequencies are what the synth hears, whatever the reference
+        assert_eq!(parse("82.41Hz", 415.0), Ok(vec![40]));
+    }
+
+    #[test]
+    fn rejects_invalid_tunings() {
+        assert!(parse("H2", STANDARD_A4).is_err());
+        assert!(parse("0Hz", STANDARD_A4).is_err());
+        assert!(parse("nanHz", STANDARD_A4).is_err());
+        assert!(parse("infHz", STANDARD_A4).is_err());
+        assert!(parse("128", STANDARD_A4).is_err());
+        assert!(parse("C-2", STANDARD_A4).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+// characters after which a new sentence starts
+const SENTENCE_ENDINGS: &[char] = &['.', '!', '?'];
+
+// only the end of the typed text matters, so older text is dropped
+const MAX_LEN: usize = 1024;
+
+// a model of the text typed since kitara started (or since the cursor
+// was last moved), used to make decisions based on what precedes the cursor
+#[derive(Default)]
+pub struct TypedText {
+    text: String,
+    // false until something was typed after a reset, when the
+    // text before the cursor is not known anymore
+    known: bool,
+    // the last character was a space typed automatically after punctuation
+    auto_spaced: bool,
+}
+
+impl TypedText {
+    pub fn push(&mut self, ch: char) {
+        self.text.push(ch);
+        self.known = true;
+        self.auto_spaced = false;
+        if self.text.len() > MAX_LEN {
+            let mut start = self.text.len() - MAX_LEN;
+            while !self.text.is_char_boundary(start) {
+                start += 1;
+            }
+            self.text.drain(..start);
+        }
+    }
+
+    pub fn push_str(&mut self, text: &str) {
+        text.chars().for_each(|ch| self.push(ch));
+    }
+
+    pub fn push_auto_space(&mut self) {
+        self.push(' ');
+        self.auto_spaced = true;
+    }
+
+    // true once right after an automatic space, which the
+    // space that is played next takes the place of
+    pub fn take_auto_space(&mut self) -> bool {
+        std::mem::replace(&mut self.auto_spaced, false)
+    }
+
+    pub fn backspace(&mut self) {
+        self.text.pop();
+        self.auto_spaced = false;
+    }
+
+    // the letters, digits and apostrophes typed right before the cursor;
+    // empty when the word starts before the known text, as it may then
+    // be the end of a longer word that kitara did not type
+    pub fn last_word(&self) -> &str {
+        let start = self.text
+            .char_indices()
+            .rev()
+            .take_while(|(_, ch)| ch.is_alphanumeric() || *ch == '\'')
+            .last()
+            .map_or(self.text.len(), |(i, _)| i);
+        match start {
+            0 => "",
+            _ => &self.text[start..],
+        }
+    }
+
+    pub fn replace_last_word(&mut self, word: &str) {
+        let start = self.text.len() - self.last_word().len();
+        self.text.truncate(start);
+        self.push_str(word);
+    }
+
+    // called when the cursor may have moved, e.g. after arrow keys or shortcuts
+    pub fn reset(&mut self) {
+        self.text.clear();
+        self.known = false;
+        self.auto_spaced = false;
+    }
+
+    // true when the last non-whitespace character ends a sentence
+    pub fn at_sentence_start(&self) -> bool {
+        self.known
+            && self.text
+                .chars()
+                .rev()
+                .find(|ch| !ch.is_whitespace())
+                .is_some_and(|ch| SENTENCE_ENDINGS.contains(&ch))
+    }
+}
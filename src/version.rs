@@ -0,0 +1,90 @@
+use std::process::Command;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const RELEASES_URL: &str = "https://api.github.com/repos/georgetheka/kitara/releases/latest";
+
+// what this build can do, so that it can be included in bug reports
+const FEATURES: &[&str] = &[
+    "text-macros",
+    "counters",
+    "layers",
+    "command-mode",
+    "focus-guard",
+    "prose",
+    "auto-correct",
+    "multi-port",
+    "dedup",
+    "cell-conditions",
+    "tunings",
+    "assign",
+    "install",
+];
+
+#[cfg(target_os = "macos")]
+const MIDI_TRANSPORT: &str = "coremidi";
+#[cfg(windows)]
+const MIDI_TRANSPORT: &str = "winmm";
+#[cfg(all(unix, not(target_os = "macos")))]
+const MIDI_TRANSPORT: &str = "alsa";
+
+#[cfg(target_os = "macos")]
+const ENIGO_BACKEND: &str = "enigo (core graphics)";
+#[cfg(windows)]
+const ENIGO_BACKEND: &str = "enigo (sendinput)";
+#[cfg(all(unix, not(target_os = "macos")))]
+const ENIGO_BACKEND: &str = "enigo (x11/xdo)";
+
+pub fn print_version(verbose: bool) {
+    println!("kitara {}", VERSION);
+    if !verbose {
+        return;
+    }
+    println!("target: {} {} ({})",
+             std::env::consts::OS,
+             std::env::consts::ARCH,
+             if cfg!(debug_assertions) { "debug" } else { "release" });
+    println!("backends: {}, dry-run", ENIGO_BACKEND);
+    println!("midi transports: {} (midir)", MIDI_TRANSPORT);
+    println!("features: {}", FEATURES.join(", "));
+}
+
+// asks the github release feed for the latest release; this is the only
+// network access kitara ever makes and it only happens when requested
+pub fn check_for_update() {
+    let output = Command::new("curl")
+        .args(["-fsSL", "--max-time", "10", "-H", "Accept: application/vnd.github+json", RELEASES_URL])
+        .output();
+    let feed = match output {
+        Ok(o) if o.status.success() => String::from_utf8_lossy(&o.stdout).into_owned(),
+        Ok(o) => return error!("Failed to check for updates - {}", String::from_utf8_lossy(&o.stderr).trim()),
+        Err(e) => return error!("Failed to check for updates (is curl installed?) - {}", e),
+    };
+
+    match latest_tag(&feed) {
+        Some(tag) if is_newer(tag, VERSION) => println!(
+            "kitara {} is available (this is {}), see https://github.com/georgetheka/kitara/releases",
+            tag, VERSION),
+        Some(_) => println!("kitara {} is up to date", VERSION),
+        None => error!("Failed to check for updates - no release found in the feed"),
+    }
+}
+
+// the feed is json, but only the value of "tag_name" is needed
+fn latest_tag(feed: &str) -> Option<&str> {
+    let after_key = &feed[feed.find("\"tag_name\"")? + "\"tag_name\"".len()..];
+    let after_quote = &after_key[after_key.find('"')? + 1..];
+    Some(&after_quote[..after_quote.find('"')?])
+}
+
+// compares dotted version numbers, e.g. v0.10.0 is newer than 0.9.1
+fn is_newer(tag: &str, current: &str) -> bool {
+    let parse = |version: &str| -> Vec<u64> {
+        version
+            .trim_start_matches('v')
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    parse(tag) > parse(current)
+}